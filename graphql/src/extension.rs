@@ -0,0 +1,118 @@
+use std::time::Duration;
+
+use graph::prelude::*;
+
+/// Observer for the different stages a query goes through while it is executed.
+///
+/// This mirrors the way `async-graphql` exposes its `Extension` trait: operators
+/// register implementations to observe query preparation (parsing and
+/// validation) and total execution latency instead of seeing a single opaque
+/// future. All callbacks have a default no-op body so an extension only has to
+/// implement the stages it cares about.
+pub trait Extension: Send + Sync {
+    /// Called right before the raw document is parsed.
+    fn parse_start(&self, _query: &str) {}
+
+    /// Called once parsing has finished.
+    fn parse_end(&self) {}
+
+    /// Called once validation has finished.
+    fn validation_end(&self) {}
+
+    /// Hook for the start of resolving the field at `path`. `GraphQlRunner`
+    /// does not dispatch this event.
+    fn resolve_start(&self, _path: &[String]) {}
+
+    /// Hook for the end of resolving the field at `path`. `GraphQlRunner`
+    /// does not dispatch this event.
+    fn resolve_end(&self, _path: &[String]) {}
+
+    /// Called once the whole query has executed, with the elapsed wall-clock time.
+    fn execution_end(&self, _elapsed: Duration) {}
+}
+
+/// Dispatcher that fans a single lifecycle event out to every registered
+/// [`Extension`], so callers can notify extensions without knowing how many
+/// are installed. `GraphQlRunner` builds one per query and uses it only around
+/// query preparation and at the end of execution; it is not part of
+/// `QueryExecutionOptions`, so `execute_prepared_query` does not notify it.
+#[derive(Clone)]
+pub struct Extensions(pub Vec<Arc<dyn Extension>>);
+
+impl Extensions {
+    pub fn parse_start(&self, query: &str) {
+        for extension in &self.0 {
+            extension.parse_start(query);
+        }
+    }
+
+    pub fn parse_end(&self) {
+        for extension in &self.0 {
+            extension.parse_end();
+        }
+    }
+
+    pub fn validation_end(&self) {
+        for extension in &self.0 {
+            extension.validation_end();
+        }
+    }
+
+    pub fn resolve_start(&self, path: &[String]) {
+        for extension in &self.0 {
+            extension.resolve_start(path);
+        }
+    }
+
+    pub fn resolve_end(&self, path: &[String]) {
+        for extension in &self.0 {
+            extension.resolve_end(path);
+        }
+    }
+
+    pub fn execution_end(&self, elapsed: Duration) {
+        for extension in &self.0 {
+            extension.execution_end(elapsed);
+        }
+    }
+}
+
+/// Built-in extension that emits structured spans for each stage into the
+/// node's `Logger`, giving operators real per-stage timing.
+pub struct TracingExtension {
+    logger: Logger,
+}
+
+impl TracingExtension {
+    pub fn new(logger: &Logger) -> Self {
+        TracingExtension {
+            logger: logger.new(o!("component" => "GraphQlTracing")),
+        }
+    }
+}
+
+impl Extension for TracingExtension {
+    fn parse_start(&self, query: &str) {
+        trace!(self.logger, "Parsing query"; "query" => query);
+    }
+
+    fn parse_end(&self) {
+        trace!(self.logger, "Parsed query");
+    }
+
+    fn validation_end(&self) {
+        trace!(self.logger, "Validated query");
+    }
+
+    fn resolve_start(&self, path: &[String]) {
+        trace!(self.logger, "Resolving field"; "path" => path.join("."));
+    }
+
+    fn resolve_end(&self, path: &[String]) {
+        trace!(self.logger, "Resolved field"; "path" => path.join("."));
+    }
+
+    fn execution_end(&self, elapsed: Duration) {
+        trace!(self.logger, "Executed query"; "elapsed_ms" => elapsed.as_millis() as u64);
+    }
+}
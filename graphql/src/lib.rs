@@ -0,0 +1,40 @@
+extern crate futures01;
+#[macro_use]
+extern crate graph;
+extern crate graphql_parser;
+extern crate indexmap;
+#[macro_use]
+extern crate lazy_static;
+extern crate lru;
+
+/// Utilities for working with GraphQL schemas.
+pub mod schema;
+
+/// Execution of GraphQL queries.
+pub mod execution;
+
+/// Introspection schema and resolver.
+pub mod introspection;
+
+/// Query runner and its execution-extension hooks.
+pub mod extension;
+pub mod query;
+pub mod runner;
+
+/// Execution of GraphQL subscriptions.
+pub mod subscription;
+
+/// Utilities for mapping the store into GraphQL values.
+pub mod store;
+
+/// Prelude that exports the most important traits and types.
+pub mod prelude {
+    pub use super::execution::*;
+    pub use super::extension::{Extension, Extensions, TracingExtension};
+    pub use super::introspection::*;
+    pub use super::query::QueryExecutionOptions;
+    pub use super::runner::GraphQlRunner;
+    pub use super::schema::*;
+    pub use super::store::*;
+    pub use super::subscription::SubscriptionExecutionOptions;
+}
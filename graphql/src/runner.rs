@@ -1,9 +1,13 @@
 use futures01::future;
 use graphql_parser::query as q;
+use lru::LruCache;
+use std::collections::{BTreeMap, HashMap, HashSet};
 use std::env;
 use std::str::FromStr;
+use std::sync::Mutex;
 use std::time::{Duration, Instant};
 
+use crate::extension::{Extension, Extensions};
 use crate::prelude::*;
 use crate::query::execute_prepared_query;
 use crate::subscription::execute_prepared_subscription;
@@ -15,6 +19,7 @@ use lazy_static::lazy_static;
 pub struct GraphQlRunner<S> {
     logger: Logger,
     store: Arc<S>,
+    extensions: Vec<Arc<dyn Extension>>,
 }
 
 lazy_static! {
@@ -38,34 +43,274 @@ lazy_static! {
         .map(|s| u32::from_str(&s)
             .unwrap_or_else(|_| panic!("failed to parse env var GRAPH_GRAPHQL_MAX_FIRST")))
         .unwrap_or(1000);
+    static ref GRAPHQL_DISABLE_INTROSPECTION: bool =
+        env::var("GRAPH_GRAPHQL_DISABLE_INTROSPECTION")
+            .ok()
+            .map(|s| bool::from_str(&s).unwrap_or_else(|_| {
+                panic!("failed to parse env var GRAPH_GRAPHQL_DISABLE_INTROSPECTION")
+            }))
+            .unwrap_or(false);
+    static ref GRAPHQL_QUERY_CACHE_SIZE: usize = env::var("GRAPH_GRAPHQL_QUERY_CACHE_SIZE")
+        .ok()
+        .map(|s| usize::from_str(&s)
+            .unwrap_or_else(|_| panic!("failed to parse env var GRAPH_GRAPHQL_QUERY_CACHE_SIZE")))
+        .unwrap_or(1000);
+    /// Cache of already parsed and validated queries, keyed on the raw document,
+    /// its variables signature and the complexity/depth limits in effect. Entries
+    /// are immutable and shared through `Arc`; cache hits still have to re-run the
+    /// `StoreResolver::at_block` step because block state changes between requests.
+    static ref QUERY_CACHE: Mutex<LruCache<String, Arc<crate::execution::Query>>> =
+        Mutex::new(LruCache::new(*GRAPHQL_QUERY_CACHE_SIZE));
+}
+
+/// Storing the prepared query in the `QUERY_CACHE` static behind an `Arc`
+/// requires it to be shareable across threads; this fails to compile if that
+/// ever stops holding.
+#[allow(dead_code)]
+fn assert_prepared_query_shareable() {
+    fn assert_send_sync<T: Send + Sync>() {}
+    assert_send_sync::<crate::execution::Query>();
+}
+
+/// Returns the selection set an operation starts from.
+fn operation_selection_set(op: &q::OperationDefinition) -> &q::SelectionSet {
+    match op {
+        q::OperationDefinition::SelectionSet(set) => set,
+        q::OperationDefinition::Query(query) => &query.selection_set,
+        q::OperationDefinition::Mutation(mutation) => &mutation.selection_set,
+        q::OperationDefinition::Subscription(subscription) => &subscription.selection_set,
+    }
+}
+
+/// A unit of work for the iterative selection-set traversal: descend into a
+/// selection set at a given depth, enter a fragment's body (pushing it onto the
+/// active expansion path), or pop a fragment off that path once its body has
+/// been fully visited.
+enum Work<'a> {
+    Visit(&'a q::SelectionSet, u32),
+    Enter(&'a q::FragmentDefinition, q::Pos, u32),
+    Pop(&'a str),
+}
+
+/// Walks the selection sets reachable from `root`, expanding fragment spreads,
+/// and fails with `RecursionLimitExceeded` as soon as the descent passes
+/// `max_depth` or a fragment spread re-enters a fragment already on the active
+/// expansion path. The traversal is driven by an explicit work-stack rather than
+/// native recursion, so a deeply nested or mutually-recursive document can never
+/// blow the real call stack regardless of how large `max_depth` is.
+fn check_selection_set<'a>(
+    root: &'a q::SelectionSet,
+    max_depth: u32,
+    fragments: &HashMap<&'a str, &'a q::FragmentDefinition>,
+) -> Result<(), QueryExecutionError> {
+    let mut work = vec![Work::Visit(root, 0)];
+    let mut on_stack: HashSet<&'a str> = HashSet::new();
+
+    while let Some(item) = work.pop() {
+        let (selection_set, depth) = match item {
+            Work::Pop(name) => {
+                on_stack.remove(name);
+                continue;
+            }
+            Work::Enter(fragment, position, depth) => {
+                // Membership is established here, when the body is actually
+                // entered, so two sibling spreads of the same fragment are not
+                // mistaken for a cycle.
+                if !on_stack.insert(fragment.name.as_str()) {
+                    return Err(QueryExecutionError::RecursionLimitExceeded(position));
+                }
+                work.push(Work::Pop(fragment.name.as_str()));
+                (&fragment.selection_set, depth)
+            }
+            Work::Visit(selection_set, depth) => (selection_set, depth),
+        };
+
+        if depth > max_depth {
+            return Err(QueryExecutionError::RecursionLimitExceeded(
+                selection_set.span.0,
+            ));
+        }
+
+        for selection in &selection_set.items {
+            match selection {
+                q::Selection::Field(field) => {
+                    work.push(Work::Visit(&field.selection_set, depth + 1));
+                }
+                q::Selection::InlineFragment(inline) => {
+                    work.push(Work::Visit(&inline.selection_set, depth + 1));
+                }
+                q::Selection::FragmentSpread(spread) => {
+                    if let Some(fragment) = fragments.get(spread.fragment_name.as_str()) {
+                        work.push(Work::Enter(fragment, spread.position, depth + 1));
+                    }
+                }
+            }
+        }
+    }
+
+    Ok(())
+}
+
+/// Rejects documents whose operations nest selection sets deeper than
+/// `max_depth` or expand a fragment recursively, before they reach the
+/// fragment-expansion path in `crate::execution::Query::new`. Fragments that no
+/// operation spreads are not walked.
+fn check_recursion_depth(
+    document: &q::Document,
+    max_depth: u8,
+) -> Result<(), QueryExecutionError> {
+    // Widen the bound to `u32` so the descent counter can never overflow, even
+    // when `max_depth` is the default `u8::max_value()` (255).
+    let max_depth = u32::from(max_depth);
+    let fragments: HashMap<&str, &q::FragmentDefinition> = document
+        .definitions
+        .iter()
+        .filter_map(|definition| match definition {
+            q::Definition::Fragment(fragment) => Some((fragment.name.as_str(), fragment)),
+            _ => None,
+        })
+        .collect();
+
+    for definition in &document.definitions {
+        if let q::Definition::Operation(op) = definition {
+            check_selection_set(operation_selection_set(op), max_depth, &fragments)?;
+        }
+    }
+
+    Ok(())
+}
+
+/// Rejects any selection targeting the `__schema`/`__type` introspection
+/// meta-fields, used when introspection has been locked down for a request.
+/// `__typename` is always allowed as it carries no schema information.
+fn check_introspection(document: &q::Document) -> Result<(), QueryExecutionError> {
+    fn walk(selection_set: &q::SelectionSet) -> Result<(), QueryExecutionError> {
+        for selection in &selection_set.items {
+            match selection {
+                q::Selection::Field(field) => {
+                    if field.name == "__schema" || field.name == "__type" {
+                        return Err(QueryExecutionError::IntrospectionDisabled(field.position));
+                    }
+                    walk(&field.selection_set)?;
+                }
+                q::Selection::InlineFragment(inline) => walk(&inline.selection_set)?,
+                q::Selection::FragmentSpread(_) => {}
+            }
+        }
+        Ok(())
+    }
+
+    for definition in &document.definitions {
+        match definition {
+            q::Definition::Operation(op) => walk(operation_selection_set(op))?,
+            q::Definition::Fragment(fragment) => walk(&fragment.selection_set)?,
+        }
+    }
+
+    Ok(())
+}
+
+/// Serializes a single query result as a GraphQL `{ data, errors }` object so it
+/// can sit alongside the other elements of a batch response, carrying its own
+/// errors independently of the rest of the batch.
+fn query_result_to_value(result: QueryResult) -> q::Value {
+    let mut fields = BTreeMap::new();
+    if let Some(data) = result.data {
+        fields.insert("data".to_owned(), data);
+    }
+    if !result.errors.is_empty() {
+        let errors = result
+            .errors
+            .iter()
+            .map(|error| {
+                let mut fields = BTreeMap::new();
+                fields.insert("message".to_owned(), q::Value::String(error.to_string()));
+                q::Value::Object(fields)
+            })
+            .collect();
+        fields.insert("errors".to_owned(), q::Value::List(errors));
+    }
+    q::Value::Object(fields)
 }
 
 impl<S> GraphQlRunner<S>
 where
     S: Store,
 {
-    /// Creates a new query runner.
-    pub fn new(logger: &Logger, store: Arc<S>) -> Self {
+    /// Creates a new query runner. `extensions` are notified when a query is
+    /// prepared (parsing and validation) and when it has executed (total
+    /// latency); per-field resolve events are not dispatched.
+    pub fn new(logger: &Logger, store: Arc<S>, extensions: Vec<Arc<dyn Extension>>) -> Self {
         GraphQlRunner {
             logger: logger.new(o!("component" => "GraphQlRunner")),
             store,
+            extensions,
         }
     }
 
+    /// Parses and validates `query`, returning the prepared form. Identical
+    /// documents served at high volume reuse the cached, already-validated
+    /// `crate::execution::Query` instead of being re-parsed on every request.
+    fn prepare_query(
+        &self,
+        query: Query,
+        max_complexity: Option<u64>,
+        max_depth: u8,
+        extensions: &Extensions,
+    ) -> Result<Arc<crate::execution::Query>, Vec<QueryExecutionError>> {
+        // The deployment/schema id is part of the key: identical document text and
+        // variables are extremely common across subgraphs, and an entry prepared
+        // for one schema must never be served against another.
+        let key = format!(
+            "{}\u{1f}{}\u{1f}{:?}\u{1f}{:?}\u{1f}{}",
+            query.schema.id, query.document, query.variables, max_complexity, max_depth
+        );
+
+        if let Some(query) = QUERY_CACHE.lock().unwrap().get(&key) {
+            // A cache hit skips parsing and validation entirely, so no parse or
+            // validation callbacks fire here.
+            return Ok(query.clone());
+        }
+
+        check_recursion_depth(&query.document, max_depth).map_err(|e| vec![e])?;
+
+        extensions.parse_start(&query.document.to_string());
+        let prepared = Arc::new(crate::execution::Query::new(
+            query,
+            max_complexity,
+            max_depth,
+        )?);
+        extensions.parse_end();
+        extensions.validation_end();
+
+        QUERY_CACHE.lock().unwrap().put(key, prepared.clone());
+        Ok(prepared)
+    }
+
     fn execute(
         &self,
         query: Query,
         max_complexity: Option<u64>,
         max_depth: Option<u8>,
         max_first: Option<u32>,
+        disable_introspection: bool,
     ) -> Result<q::Value, Vec<QueryExecutionError>> {
         let max_depth = max_depth.unwrap_or(*GRAPHQL_MAX_DEPTH);
-        let query = crate::execution::Query::new(query, max_complexity, max_depth)?;
+        let extensions = Extensions(self.extensions.clone());
+        let started = Instant::now();
+
+        if disable_introspection {
+            check_introspection(&query.document).map_err(|e| vec![e])?;
+        }
+        let query = self.prepare_query(query, max_complexity, max_depth, &extensions)?;
         let bc = query.block_constraint()?;
         let resolver =
             StoreResolver::at_block(&self.logger, self.store.clone(), bc, &query.schema.id)?;
-        execute_prepared_query(
-            query,
+        // The cache hands back a shared `Arc`; `execute_prepared_query` consumes a
+        // `Query` by value, so clone the prepared form out of the `Arc` for this
+        // request while the cached entry stays available for the next one.
+        let result = execute_prepared_query(
+            (*query).clone(),
             QueryExecutionOptions {
                 logger: self.logger.clone(),
                 resolver,
@@ -74,7 +319,20 @@ where
                 max_depth: max_depth,
                 max_first: max_first.unwrap_or(*GRAPHQL_MAX_FIRST),
             },
-        )
+        );
+        extensions.execution_end(started.elapsed());
+        result
+    }
+}
+
+/// Clamps a caller-supplied complexity cap so it can only tighten, never loosen,
+/// the configured maximum. `None` means "unlimited", so a per-request cap may
+/// tighten an otherwise unbounded limit.
+fn effective_complexity(requested: Option<u64>, max: Option<u64>) -> Option<u64> {
+    match (requested, max) {
+        (Some(requested), Some(max)) => Some(requested.min(max)),
+        (Some(requested), None) => Some(requested),
+        (None, max) => max,
     }
 }
 
@@ -98,10 +356,48 @@ where
         max_depth: Option<u8>,
         max_first: Option<u32>,
     ) -> QueryResultFuture {
-        let result = QueryResult::from(self.execute(query, max_complexity, max_depth, max_first));
+        // Prefer the caller-supplied per-request caps, but clamp them so a caller
+        // can only ever tighten, never loosen, the configured maximums. This lets
+        // an embedding service hand stricter limits to anonymous traffic and
+        // looser ones to authenticated callers without restarting the node.
+        let max_complexity = effective_complexity(max_complexity, *GRAPHQL_MAX_COMPLEXITY);
+        let max_depth = Some(max_depth.unwrap_or(*GRAPHQL_MAX_DEPTH).min(*GRAPHQL_MAX_DEPTH));
+        let max_first = Some(max_first.unwrap_or(*GRAPHQL_MAX_FIRST).min(*GRAPHQL_MAX_FIRST));
+        let result = QueryResult::from(self.execute(
+            query,
+            max_complexity,
+            max_depth,
+            max_first,
+            *GRAPHQL_DISABLE_INTROSPECTION,
+        ));
         Box::new(future::ok(result))
     }
 
+    fn run_query_batch(&self, queries: Vec<Query>) -> QueryResultFuture {
+        // Execute each operation independently, re-running `StoreResolver::at_block`
+        // per element so queries pinned to different block constraints still work.
+        // Each element carries its own errors so one failing query does not fail the
+        // whole batch, and the shared complexity/depth/first limits apply
+        // independently per element.
+        let elements = queries
+            .into_iter()
+            .map(|query| {
+                let result = QueryResult::from(self.execute(
+                    query,
+                    *GRAPHQL_MAX_COMPLEXITY,
+                    Some(*GRAPHQL_MAX_DEPTH),
+                    Some(*GRAPHQL_MAX_FIRST),
+                    *GRAPHQL_DISABLE_INTROSPECTION,
+                ));
+                query_result_to_value(result)
+            })
+            .collect();
+
+        Box::new(future::ok(QueryResult::new(Some(q::Value::List(
+            elements,
+        )))))
+    }
+
     fn run_subscription(&self, subscription: Subscription) -> SubscriptionResultFuture {
         let query = match crate::execution::Query::new(
             subscription.query,